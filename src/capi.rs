@@ -0,0 +1,90 @@
+//! C ABI surface for embedding `find_malloc`'s export transform into
+//! non-Rust build pipelines. Gated behind the `capi` feature.
+
+use crate::{export_allocator, AllocatorExportConfig};
+use std::slice;
+use wasmbin::Module;
+
+/// Result of [`find_malloc_export_allocator`].
+///
+/// On success (`error_code == 0`), `data`/`len` describe the transformed
+/// wasm module and must be released with [`find_malloc_free_result`]. On
+/// failure, `data` is null and `len` is zero.
+#[repr(C)]
+pub struct FindMallocResult {
+    pub data: *mut u8,
+    pub len: usize,
+    pub error_code: i32,
+}
+
+const ERR_NULL_INPUT: i32 = 1;
+const ERR_DECODE: i32 = 2;
+const ERR_EXPORT: i32 = 3;
+const ERR_ENCODE: i32 = 4;
+
+fn error_result(error_code: i32) -> FindMallocResult {
+    FindMallocResult {
+        data: std::ptr::null_mut(),
+        len: 0,
+        error_code,
+    }
+}
+
+/// Decodes `input[..input_len]` as a wasm module, adds the default
+/// allocator exports (see [`AllocatorExportConfig::default`]), and
+/// re-encodes it.
+///
+/// # Safety
+///
+/// `input` must be valid for reads of `input_len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn find_malloc_export_allocator(
+    input: *const u8,
+    input_len: usize,
+) -> FindMallocResult {
+    if input.is_null() {
+        return error_result(ERR_NULL_INPUT);
+    }
+    let bytes = slice::from_raw_parts(input, input_len);
+
+    let module = match Module::decode_from(bytes) {
+        Ok(module) => module,
+        Err(_) => return error_result(ERR_DECODE),
+    };
+
+    let (module, _summary) = match export_allocator(module, &AllocatorExportConfig::default()) {
+        Ok(result) => result,
+        Err(_) => return error_result(ERR_EXPORT),
+    };
+
+    let mut encoded = Vec::new();
+    if module.encode_into(&mut encoded).is_err() {
+        return error_result(ERR_ENCODE);
+    }
+
+    let mut encoded = encoded.into_boxed_slice();
+    let data = encoded.as_mut_ptr();
+    let len = encoded.len();
+    std::mem::forget(encoded);
+
+    FindMallocResult {
+        data,
+        len,
+        error_code: 0,
+    }
+}
+
+/// Releases the buffer in a [`FindMallocResult`] previously returned by
+/// [`find_malloc_export_allocator`]. A null `data` is a no-op.
+///
+/// # Safety
+///
+/// `result` must be a value previously returned by
+/// [`find_malloc_export_allocator`] that hasn't already been freed.
+#[no_mangle]
+pub unsafe extern "C" fn find_malloc_free_result(result: FindMallocResult) {
+    if result.data.is_null() {
+        return;
+    }
+    drop(Vec::from_raw_parts(result.data, result.len, result.len));
+}
@@ -0,0 +1,266 @@
+//! Translates a raw decode-failure byte offset into a human-readable
+//! location by re-walking the section framing that was already consumed.
+
+use std::fmt;
+
+/// Where a decode failure occurred, in terms a reader can act on rather than
+/// a bare file offset.
+#[derive(Debug, Clone)]
+pub struct DecodeLocation {
+    pub offset: u64,
+    pub breadcrumbs: Vec<String>,
+}
+
+impl fmt::Display for DecodeLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "at offset 0x{:08X}", self.offset)?;
+        if !self.breadcrumbs.is_empty() {
+            write!(f, " ({})", self.breadcrumbs.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Where diagnostic messages go as decoding proceeds.
+pub trait DiagnosticSink {
+    fn report(&mut self, breadcrumb: String);
+}
+
+/// Discards every breadcrumb; use when the caller only wants the final
+/// [`DecodeLocation`] and not a running trace.
+pub struct SilentSink;
+
+impl DiagnosticSink for SilentSink {
+    fn report(&mut self, _breadcrumb: String) {}
+}
+
+/// Logs each breadcrumb as it's produced, via the `log` crate at debug
+/// level, so a verbose run can be followed section by section.
+pub struct VerboseSink;
+
+impl DiagnosticSink for VerboseSink {
+    fn report(&mut self, breadcrumb: String) {
+        log::debug!("{}", breadcrumb);
+    }
+}
+
+/// Walks the section and function-body framing of `bytes` up to `offset`,
+/// reporting breadcrumbs to `sink` as it goes, and returns the most precise
+/// location it could establish.
+///
+/// `offset` is the raw byte position reported by `stream_position()` on a
+/// `wasmbin` decode failure. Framing here is re-derived independently of
+/// `wasmbin`'s own (already-failed) decode, so it only needs to get as far
+/// as `offset` before giving up.
+pub fn locate(bytes: &[u8], offset: u64, sink: &mut dyn DiagnosticSink) -> DecodeLocation {
+    let offset = offset as usize;
+    let mut breadcrumbs = Vec::new();
+
+    if bytes.len() < 8 || offset < 8 {
+        return DecodeLocation {
+            offset: offset as u64,
+            breadcrumbs,
+        };
+    }
+
+    let mut pos = 8; // past the magic number and version.
+    while pos < bytes.len() {
+        let Some(&section_id) = bytes.get(pos) else {
+            break;
+        };
+        let Some((size, body_start)) = read_u32_leb128(bytes, pos + 1) else {
+            break;
+        };
+        let size = size as usize;
+        let body_end = body_start + size;
+
+        if offset < body_start {
+            // The failure is in this section's id/size header itself.
+            break;
+        }
+
+        let name = section_name(section_id);
+        if offset >= body_start && offset < body_end.min(bytes.len()) {
+            let breadcrumb = format!("in {} section", name);
+            sink.report(breadcrumb.clone());
+            breadcrumbs.push(breadcrumb);
+
+            if section_id == 10 {
+                locate_in_code_section(bytes, body_start, offset, sink, &mut breadcrumbs);
+            }
+            break;
+        }
+
+        let breadcrumb = format!("after {} section", name);
+        sink.report(breadcrumb);
+        pos = body_end;
+    }
+
+    DecodeLocation {
+        offset: offset as u64,
+        breadcrumbs,
+    }
+}
+
+fn locate_in_code_section(
+    bytes: &[u8],
+    section_start: usize,
+    offset: usize,
+    sink: &mut dyn DiagnosticSink,
+    breadcrumbs: &mut Vec<String>,
+) {
+    let Some((count, mut cursor)) = read_u32_leb128(bytes, section_start) else {
+        return;
+    };
+
+    for func_index in 0..count {
+        if cursor >= bytes.len() {
+            break;
+        }
+        let Some((body_size, body_start)) = read_u32_leb128(bytes, cursor) else {
+            break;
+        };
+        let body_size = body_size as usize;
+        let body_end = body_start + body_size;
+
+        if offset >= body_start && offset < body_end.min(bytes.len()) {
+            let breadcrumb = format!("function body #{}", func_index);
+            sink.report(breadcrumb.clone());
+            breadcrumbs.push(breadcrumb);
+
+            if let Some((local_groups, mut locals_end)) = read_u32_leb128(bytes, body_start) {
+                // Each local-decl entry is a count varint followed by a
+                // one-byte value type; an entry that can't be framed is
+                // treated as running to the end of the body.
+                for _ in 0..local_groups {
+                    match read_u32_leb128(bytes, locals_end) {
+                        Some((_, type_pos)) if type_pos < body_end => locals_end = type_pos + 1,
+                        _ => {
+                            locals_end = body_end;
+                            break;
+                        }
+                    }
+                }
+                if offset < locals_end {
+                    let breadcrumb = "at local-decl vector".to_owned();
+                    sink.report(breadcrumb.clone());
+                    breadcrumbs.push(breadcrumb);
+                } else {
+                    let breadcrumb = "in instruction stream".to_owned();
+                    sink.report(breadcrumb.clone());
+                    breadcrumbs.push(breadcrumb);
+                }
+            }
+            return;
+        }
+
+        cursor = body_end;
+    }
+}
+
+fn section_name(id: u8) -> &'static str {
+    match id {
+        0 => "Custom",
+        1 => "Type",
+        2 => "Import",
+        3 => "Function",
+        4 => "Table",
+        5 => "Memory",
+        6 => "Global",
+        7 => "Export",
+        8 => "Start",
+        9 => "Element",
+        10 => "Code",
+        11 => "Data",
+        12 => "DataCount",
+        _ => "Unknown",
+    }
+}
+
+fn read_u32_leb128(data: &[u8], pos: usize) -> Option<(u32, usize)> {
+    let mut result: u32 = 0;
+    let mut shift = 0;
+    for (i, &byte) in data.get(pos..)?.iter().enumerate() {
+        result |= ((byte & 0x7f) as u32) << shift;
+        if byte & 0x80 == 0 {
+            return Some((result, pos + i + 1));
+        }
+        shift += 7;
+        if shift >= 32 {
+            return None;
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An 8-byte header followed by a Code section with one function body
+    /// (a single zero-length local-decl varint plus an `end` opcode), then a
+    /// trailing section with an id this tool doesn't recognize by name.
+    fn sample_bytes() -> Vec<u8> {
+        let mut bytes = vec![0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00]; // header
+        bytes.extend_from_slice(&[10, 0x04, 0x01, 0x02, 0x00, 0x0B]); // Code section
+        bytes.extend_from_slice(&[99, 0x02, 0xAA, 0xBB]); // trailing unknown section
+        bytes
+    }
+
+    #[test]
+    fn locates_local_decl_vector_inside_a_function_body() {
+        let bytes = sample_bytes();
+        let location = locate(&bytes, 12, &mut SilentSink);
+        assert_eq!(
+            location.breadcrumbs,
+            vec!["in Code section", "function body #0", "at local-decl vector"]
+        );
+    }
+
+    #[test]
+    fn locates_instruction_stream_inside_a_function_body() {
+        let bytes = sample_bytes();
+        let location = locate(&bytes, 13, &mut SilentSink);
+        assert_eq!(
+            location.breadcrumbs,
+            vec!["in Code section", "function body #0", "in instruction stream"]
+        );
+    }
+
+    #[test]
+    fn locates_trailing_unknown_section_after_the_code_section() {
+        // "after X section" breadcrumbs are reported to the sink as the walk
+        // passes through, but only the final, most precise location is kept
+        // in the returned breadcrumb trail.
+        let bytes = sample_bytes();
+        let location = locate(&bytes, 16, &mut SilentSink);
+        assert_eq!(location.breadcrumbs, vec!["in Unknown section"]);
+    }
+
+    #[test]
+    fn offset_before_the_header_produces_no_breadcrumbs() {
+        let bytes = sample_bytes();
+        let location = locate(&bytes, 4, &mut SilentSink);
+        assert!(location.breadcrumbs.is_empty());
+        assert_eq!(location.offset, 4);
+    }
+
+    /// Collects every breadcrumb reported along the way, including the
+    /// "after X section" ones that don't make it into the final
+    /// [`DecodeLocation`].
+    struct RecordingSink(Vec<String>);
+
+    impl DiagnosticSink for RecordingSink {
+        fn report(&mut self, breadcrumb: String) {
+            self.0.push(breadcrumb);
+        }
+    }
+
+    #[test]
+    fn sink_sees_sections_walked_past_before_the_final_location() {
+        let bytes = sample_bytes();
+        let mut sink = RecordingSink(Vec::new());
+        locate(&bytes, 16, &mut sink);
+        assert_eq!(sink.0, vec!["after Code section", "in Unknown section"]);
+    }
+}
@@ -0,0 +1,499 @@
+use anyhow::{bail, Result};
+use std::collections::HashSet;
+use wasmbin::sections::{self, payload, CustomSection, Export, ExportDesc, NameSubSection, Section};
+use wasmbin::Module;
+
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod diagnostics;
+
+/// The allocator entry points this tool knows how to look for and export.
+pub const ALLOCATOR_SYMBOLS: &[&str] = &["malloc", "free", "realloc", "calloc", "aligned_alloc"];
+
+/// One requested export: find the function named `symbol` and export it as
+/// `export_name`.
+#[derive(Debug, Clone)]
+pub struct AllocatorExport {
+    pub symbol: String,
+    pub export_name: String,
+}
+
+/// Configures which allocator functions [`export_allocator`] should expose,
+/// and under what export names.
+#[derive(Debug, Clone)]
+pub struct AllocatorExportConfig {
+    pub exports: Vec<AllocatorExport>,
+}
+
+impl Default for AllocatorExportConfig {
+    /// Requests the full allocator quartet (plus `aligned_alloc`), each
+    /// exported under its own name.
+    fn default() -> Self {
+        AllocatorExportConfig {
+            exports: ALLOCATOR_SYMBOLS
+                .iter()
+                .map(|&symbol| AllocatorExport {
+                    symbol: symbol.to_owned(),
+                    export_name: symbol.to_owned(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// What [`export_allocator`] did with each requested symbol.
+#[derive(Debug, Clone, Default)]
+pub struct AllocatorExportSummary {
+    /// `(symbol, export_name)` pairs that were newly exported.
+    pub added: Vec<(String, String)>,
+    /// Symbols whose requested export name was already taken, func or not.
+    pub already_exported: Vec<String>,
+    /// Symbols that could not be resolved to a function index.
+    pub not_found: Vec<String>,
+}
+
+/// Adds exports for each entry in `config` that isn't already exported under
+/// its requested name, resolving the target function via its existing
+/// export or the name custom section.
+pub fn export_allocator(
+    mut m: Module,
+    config: &AllocatorExportConfig,
+) -> Result<(Module, AllocatorExportSummary)> {
+    let mut summary = AllocatorExportSummary::default();
+    let mut to_add = Vec::new();
+    let mut claimed: HashSet<String> = list_exports(&m)?.into_iter().collect();
+
+    for request in &config.exports {
+        // A name collision blocks the export regardless of what kind of
+        // export already holds it: re-exporting a table/memory/global name
+        // as a function would produce a module with two exports sharing a
+        // name, which no runtime accepts. `claimed` is updated as each
+        // request is accepted (not just seeded from `m`), so two requests in
+        // the same config that share an export_name don't both pass this
+        // check before `m` itself is mutated below.
+        if claimed.contains(&request.export_name) {
+            summary.already_exported.push(request.symbol.clone());
+            continue;
+        }
+
+        match export_func_index(&m, &request.symbol).or_else(|| resolve_function_by_name(&m, &request.symbol))
+        {
+            Some(func_index) => {
+                claimed.insert(request.export_name.clone());
+                to_add.push((request, func_index));
+            }
+            None => summary.not_found.push(request.symbol.clone()),
+        }
+    }
+
+    if !to_add.is_empty() {
+        let exports = m
+            .find_or_insert_std_section::<payload::Export>(Vec::new)
+            .try_contents_mut()?;
+        for (request, func_index) in to_add {
+            exports.push(Export {
+                name: request.export_name.clone(),
+                desc: ExportDesc::Func(func_index.into()),
+            });
+            summary
+                .added
+                .push((request.symbol.clone(), request.export_name.clone()));
+        }
+    }
+
+    Ok((m, summary))
+}
+
+/// A snapshot of one allocator symbol's visibility in a module.
+#[derive(Debug, Clone)]
+pub struct AllocatorStatus {
+    pub symbol: &'static str,
+    pub func_index: Option<u32>,
+    pub already_exported: bool,
+}
+
+/// The result of a non-mutating inspection of a module, see [`scan`].
+#[derive(Debug, Clone)]
+pub struct ScanReport {
+    pub exports: Vec<String>,
+    pub imports: Vec<String>,
+    pub allocators: Vec<AllocatorStatus>,
+}
+
+/// Surveys `m` without modifying it: every export and import name, and for
+/// each symbol in [`ALLOCATOR_SYMBOLS`] whether it's present, already
+/// exported, and which function index it resolves to via the name custom
+/// section.
+///
+/// Intended as a diagnostic step before deciding whether [`export_allocator`]
+/// is even needed.
+pub fn scan(m: &Module) -> Result<ScanReport> {
+    let exports = list_exports(m)?;
+    let imports = list_imports(m)?;
+
+    let allocators = ALLOCATOR_SYMBOLS
+        .iter()
+        .map(|&symbol| AllocatorStatus {
+            symbol,
+            func_index: resolve_function_by_name(m, symbol),
+            already_exported: has_export_named(m, symbol),
+        })
+        .collect();
+
+    Ok(ScanReport {
+        exports,
+        imports,
+        allocators,
+    })
+}
+
+/// Re-decodes `encoded` and structurally compares the result against `m`,
+/// the in-memory module it was produced from, failing loudly if they
+/// differ.
+///
+/// This catches encoder bugs that would otherwise only surface once a
+/// downstream runtime rejects the file: a mismatch here means `encode_into`
+/// silently dropped or corrupted something that was present in `m`.
+pub fn verify_round_trip(m: &Module, encoded: &[u8]) -> Result<()> {
+    let decoded = Module::decode_from(encoded)
+        .map_err(|err| anyhow::anyhow!("round-trip verification failed to re-decode: {}", err))?;
+
+    if decoded != *m {
+        bail!(
+            "round-trip verification failed: re-decoding the encoded module produced a \
+             structurally different module than the one that was encoded"
+        );
+    }
+
+    Ok(())
+}
+
+/// Custom sections this tool considers safe to drop by default: the name
+/// section, DWARF debug sections, and the producers section.
+fn is_strippable_by_default(name: &str) -> bool {
+    name == "name" || name == "producers" || name.starts_with(".debug_")
+}
+
+/// What [`strip_custom_sections`] removed.
+#[derive(Debug, Clone, Default)]
+pub struct StripSummary {
+    pub removed: Vec<String>,
+}
+
+/// Drops custom sections whose name is in [`is_strippable_by_default`] and
+/// not in `keep`, so the remaining encode doesn't carry their bytes.
+///
+/// Bytes saved is the difference between the input and re-encoded output
+/// sizes, which the caller is in the best position to measure.
+pub fn strip_custom_sections(m: Module, keep: &[String]) -> Result<(Module, StripSummary)> {
+    let mut summary = StripSummary::default();
+    let mut sections = Vec::with_capacity(m.sections.len());
+
+    for section in m.sections {
+        if let Section::Custom(custom) = &section {
+            let name = custom.try_contents()?.name().to_owned();
+            if is_strippable_by_default(&name) && !keep.iter().any(|k| k == &name) {
+                summary.removed.push(name);
+                continue;
+            }
+        }
+        sections.push(section);
+    }
+
+    Ok((
+        Module {
+            magic_and_version: m.magic_and_version,
+            sections,
+        },
+        summary,
+    ))
+}
+
+fn list_exports(m: &Module) -> Result<Vec<String>> {
+    let Some(section) = m.find_std_section::<payload::Export>() else {
+        return Ok(Vec::new());
+    };
+    Ok(section
+        .try_contents()?
+        .iter()
+        .map(|export| export.name.clone())
+        .collect())
+}
+
+fn list_imports(m: &Module) -> Result<Vec<String>> {
+    let Some(section) = m.find_std_section::<payload::Import>() else {
+        return Ok(Vec::new());
+    };
+    Ok(section
+        .try_contents()?
+        .iter()
+        .map(|import| format!("{}::{}", import.path.module, import.path.name))
+        .collect())
+}
+
+fn find_export_by_name<'a>(m: &'a Module, name: &str) -> Option<&'a Export> {
+    let section = m.find_std_section::<payload::Export>()?;
+    let exports = section.try_contents().ok()?;
+    exports.iter().find(|export| export.name == name)
+}
+
+/// Whether `name` is already taken by an export, of any kind.
+fn has_export_named(m: &Module, name: &str) -> bool {
+    find_export_by_name(m, name).is_some()
+}
+
+/// The function index already exported under `name`, if any; `None` both
+/// when there's no such export and when it exports something other than a
+/// function.
+fn export_func_index(m: &Module, name: &str) -> Option<u32> {
+    match find_export_by_name(m, name)?.desc {
+        ExportDesc::Func(id) => Some(id.index),
+        _ => None,
+    }
+}
+
+/// Resolves `name` to a function index using the `name` custom section's
+/// function-name subsection, which maps `(func_index, name)` pairs.
+///
+/// Function indices in the name subsection live in the same space as
+/// everywhere else in the module: imported functions occupy the low indices,
+/// followed by locally-defined functions in declaration order. This resolver
+/// additionally checks the index against the total function count (imports
+/// plus definitions) so a corrupt or stale name section can't produce an
+/// out-of-range export.
+pub fn resolve_function_by_name(m: &Module, name: &str) -> Option<u32> {
+    let index = find_in_name_section(m, name)?;
+    if index >= total_function_count(m) {
+        return None;
+    }
+    Some(index)
+}
+
+fn total_function_count(m: &Module) -> u32 {
+    let imported = m
+        .find_std_section::<payload::Import>()
+        .and_then(|section| section.try_contents().ok())
+        .map(|imports| {
+            imports
+                .iter()
+                .filter(|import| matches!(import.desc, sections::ImportDesc::Func(_)))
+                .count()
+        })
+        .unwrap_or(0);
+    let defined = m
+        .find_std_section::<payload::Function>()
+        .and_then(|section| section.try_contents().ok())
+        .map(|functions| functions.len())
+        .unwrap_or(0);
+    (imported + defined) as u32
+}
+
+fn find_in_name_section(m: &Module, name: &str) -> Option<u32> {
+    for section in &m.sections {
+        let Section::Custom(custom) = section else {
+            continue;
+        };
+        let Ok(CustomSection::Name(subsections)) = custom.try_contents() else {
+            continue;
+        };
+        let Ok(subsections) = subsections.try_contents() else {
+            continue;
+        };
+        for subsection in subsections {
+            let NameSubSection::Func(map) = subsection else {
+                continue;
+            };
+            let Ok(map) = map.try_contents() else {
+                continue;
+            };
+            if let Some(assoc) = map.items.iter().find(|assoc| assoc.value == name) {
+                return Some(assoc.index.index);
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasmbin::indices::{FuncId, TypeId};
+    use wasmbin::sections::{Import, ImportDesc, ImportPath, NameAssoc, NameMap, ProducerField};
+    use wasmbin::types::{FuncType, ValueType};
+
+    fn name_section(funcs: &[(u32, &str)]) -> Section {
+        let map = NameMap {
+            items: funcs
+                .iter()
+                .map(|&(index, name)| NameAssoc {
+                    index: FuncId::from(index),
+                    value: name.to_owned(),
+                })
+                .collect(),
+        };
+        Section::Custom(
+            CustomSection::Name(vec![NameSubSection::Func(map.into())].into()).into(),
+        )
+    }
+
+    fn module_with_sections(sections: Vec<Section>) -> Module {
+        Module {
+            magic_and_version: Default::default(),
+            sections,
+        }
+    }
+
+    #[test]
+    fn resolves_function_without_export() {
+        let m = module_with_sections(vec![
+            Section::Function(vec![TypeId::from(0)].into()),
+            name_section(&[(0, "malloc")]),
+        ]);
+        assert_eq!(resolve_function_by_name(&m, "malloc"), Some(0));
+        assert_eq!(resolve_function_by_name(&m, "free"), None);
+    }
+
+    #[test]
+    fn rejects_out_of_range_name_section_entry() {
+        // Name section claims index 5, but there are no imported or defined
+        // functions at all.
+        let m = module_with_sections(vec![name_section(&[(5, "malloc")])]);
+        assert_eq!(resolve_function_by_name(&m, "malloc"), None);
+    }
+
+    #[test]
+    fn total_function_count_includes_imports() {
+        let m = module_with_sections(vec![
+            Section::Import(
+                vec![Import {
+                    path: ImportPath {
+                        module: "env".to_owned(),
+                        name: "imported_fn".to_owned(),
+                    },
+                    desc: ImportDesc::Func(TypeId::from(0)),
+                }]
+                .into(),
+            ),
+            Section::Function(vec![TypeId::from(0)].into()),
+            name_section(&[(1, "malloc")]),
+        ]);
+        assert_eq!(resolve_function_by_name(&m, "malloc"), Some(1));
+    }
+
+    #[test]
+    fn export_allocator_adds_missing_export() {
+        let m = module_with_sections(vec![
+            Section::Type(vec![FuncType {
+                params: vec![ValueType::I32],
+                results: vec![ValueType::I32],
+            }]
+            .into()),
+            Section::Function(vec![TypeId::from(0)].into()),
+            name_section(&[(0, "malloc")]),
+        ]);
+
+        let config = AllocatorExportConfig {
+            exports: vec![AllocatorExport {
+                symbol: "malloc".to_owned(),
+                export_name: "malloc".to_owned(),
+            }],
+        };
+        let (m, summary) = export_allocator(m, &config).unwrap();
+        assert_eq!(summary.added, vec![("malloc".to_owned(), "malloc".to_owned())]);
+        assert!(summary.already_exported.is_empty());
+        assert!(summary.not_found.is_empty());
+        assert_eq!(export_func_index(&m, "malloc"), Some(0));
+    }
+
+    #[test]
+    fn export_allocator_reports_collision_on_non_func_export() {
+        let m = module_with_sections(vec![Section::Export(
+            vec![Export {
+                name: "malloc".to_owned(),
+                desc: ExportDesc::Mem(0u32.into()),
+            }]
+            .into(),
+        )]);
+
+        let config = AllocatorExportConfig {
+            exports: vec![AllocatorExport {
+                symbol: "malloc".to_owned(),
+                export_name: "malloc".to_owned(),
+            }],
+        };
+        let (_, summary) = export_allocator(m, &config).unwrap();
+        assert_eq!(summary.already_exported, vec!["malloc".to_owned()]);
+        assert!(summary.added.is_empty());
+    }
+
+    #[test]
+    fn export_allocator_reports_not_found() {
+        let m = module_with_sections(vec![]);
+        let config = AllocatorExportConfig {
+            exports: vec![AllocatorExport {
+                symbol: "malloc".to_owned(),
+                export_name: "malloc".to_owned(),
+            }],
+        };
+        let (_, summary) = export_allocator(m, &config).unwrap();
+        assert_eq!(summary.not_found, vec!["malloc".to_owned()]);
+    }
+
+    #[test]
+    fn export_allocator_rejects_duplicate_export_name_in_same_batch() {
+        let m = module_with_sections(vec![
+            Section::Type(vec![FuncType {
+                params: vec![ValueType::I32],
+                results: vec![ValueType::I32],
+            }]
+            .into()),
+            Section::Function(vec![TypeId::from(0), TypeId::from(0)].into()),
+            name_section(&[(0, "malloc"), (1, "dlmalloc")]),
+        ]);
+
+        let config = AllocatorExportConfig {
+            exports: vec![
+                AllocatorExport {
+                    symbol: "malloc".to_owned(),
+                    export_name: "foo".to_owned(),
+                },
+                AllocatorExport {
+                    symbol: "dlmalloc".to_owned(),
+                    export_name: "foo".to_owned(),
+                },
+            ],
+        };
+        let (m, summary) = export_allocator(m, &config).unwrap();
+        assert_eq!(summary.added, vec![("malloc".to_owned(), "foo".to_owned())]);
+        assert_eq!(summary.already_exported, vec!["dlmalloc".to_owned()]);
+        assert_eq!(list_exports(&m).unwrap(), vec!["foo".to_owned()]);
+    }
+
+    #[test]
+    fn strip_removes_name_but_keeps_whitelisted() {
+        let m = module_with_sections(vec![
+            name_section(&[(0, "malloc")]),
+            Section::Custom(CustomSection::Producers(Vec::<ProducerField>::new().into()).into()),
+        ]);
+        let (m, summary) = strip_custom_sections(m, &["producers".to_owned()]).unwrap();
+        assert_eq!(summary.removed, vec!["name".to_owned()]);
+        assert_eq!(m.sections.len(), 1);
+    }
+
+    #[test]
+    fn verify_round_trip_accepts_self_consistent_encoding() {
+        let m = module_with_sections(vec![Section::Function(vec![TypeId::from(0)].into())]);
+        let mut encoded = Vec::new();
+        m.encode_into(&mut encoded).unwrap();
+        verify_round_trip(&m, &encoded).unwrap();
+    }
+
+    #[test]
+    fn verify_round_trip_rejects_mismatched_module() {
+        let m = module_with_sections(vec![Section::Function(vec![TypeId::from(0)].into())]);
+        let other = module_with_sections(vec![]);
+        let mut encoded = Vec::new();
+        m.encode_into(&mut encoded).unwrap();
+        assert!(verify_round_trip(&other, &encoded).is_err());
+    }
+}
@@ -1,29 +1,164 @@
 use anyhow::Context;
-use find_malloc::export_malloc;
+use find_malloc::{scan, AllocatorExport, AllocatorExportConfig};
 use std::fs::File;
-use std::io::{BufReader, Seek};
+use std::io::{Cursor, Seek};
 use structopt::StructOpt;
 use wasmbin::Module;
 
 #[derive(StructOpt)]
-struct DumpOpts {
+enum Opts {
+    /// Add allocator exports (by default `malloc`, `free`, `realloc`,
+    /// `calloc` and `aligned_alloc`) to a wasm module and write out the result.
+    Export(ExportOpts),
+    /// Inspect a wasm module's exports, imports and allocator functions
+    /// without modifying it.
+    Scan(ScanOpts),
+}
+
+#[derive(StructOpt)]
+struct ExportOpts {
     filename: String,
     output_filename: String,
+    /// Export an allocator symbol under a chosen name, as `name=symbol`,
+    /// e.g. `--export malloc=dlmalloc`. May be given multiple times; if
+    /// omitted, the full allocator quartet (plus `aligned_alloc`) is
+    /// exported under its own names.
+    #[structopt(long = "export", parse(try_from_str = parse_export_flag))]
+    exports: Vec<AllocatorExport>,
+    /// Re-decode the encoded output and structurally compare it against the
+    /// in-memory module that produced it, failing if they don't match.
+    #[structopt(long)]
+    verify: bool,
+    /// On a decode failure, also log each section walked past while locating
+    /// it (at debug level). The error message names the section and function
+    /// body either way.
+    #[structopt(long)]
+    verbose_errors: bool,
+    /// Drop custom sections (name, debug info, producers) in the same pass
+    /// as adding allocator exports.
+    #[structopt(long)]
+    strip: bool,
+    /// When stripping, keep a custom section that would otherwise be
+    /// dropped, by name. May be given multiple times.
+    #[structopt(long = "keep-section")]
+    keep_sections: Vec<String>,
+}
+
+fn parse_export_flag(s: &str) -> anyhow::Result<AllocatorExport> {
+    let (export_name, symbol) = s
+        .split_once('=')
+        .with_context(|| format!("expected `name=symbol`, got `{}`", s))?;
+    Ok(AllocatorExport {
+        symbol: symbol.to_owned(),
+        export_name: export_name.to_owned(),
+    })
+}
+
+#[derive(StructOpt)]
+struct ScanOpts {
+    filename: String,
+    /// On a decode failure, also log each section walked past while locating
+    /// it (at debug level). The error message names the section and function
+    /// body either way.
+    #[structopt(long)]
+    verbose_errors: bool,
+}
+
+fn decode_module(filename: &str, verbose_errors: bool) -> anyhow::Result<Module> {
+    let bytes = std::fs::read(filename)?;
+    let mut f = Cursor::new(bytes.as_slice());
+    match Module::decode_from(&mut f) {
+        Ok(m) => Ok(m),
+        Err(err) => {
+            let offset = f.stream_position().unwrap();
+            let location = if verbose_errors {
+                find_malloc::diagnostics::locate(&bytes, offset, &mut find_malloc::diagnostics::VerboseSink)
+            } else {
+                find_malloc::diagnostics::locate(&bytes, offset, &mut find_malloc::diagnostics::SilentSink)
+            };
+            Err(err).with_context(|| format!("Parsing error {}", location))
+        }
+    }
 }
 
 fn main() -> anyhow::Result<()> {
     env_logger::init();
-    let opts = DumpOpts::from_args();
-    let f = File::open(&opts.filename)?;
-    let mut f = BufReader::new(f);
-    let m = Module::decode_from(&mut f).with_context(|| {
-        format!(
-            "Parsing error at offset 0x{:08X}",
-            f.stream_position().unwrap()
-        )
-    })?;
-
-    let m = export_malloc(m).unwrap();
-    m.encode_into(File::create(opts.output_filename)?)?;
+    match Opts::from_args() {
+        Opts::Export(opts) => {
+            let m = decode_module(&opts.filename, opts.verbose_errors)?;
+            let config = if opts.exports.is_empty() {
+                AllocatorExportConfig::default()
+            } else {
+                AllocatorExportConfig {
+                    exports: opts.exports,
+                }
+            };
+            let (m, summary) = find_malloc::export_allocator(m, &config)?;
+            for (symbol, export_name) in &summary.added {
+                log::info!("exported `{}` as `{}`", symbol, export_name);
+            }
+            for symbol in &summary.not_found {
+                log::warn!("could not find a function for `{}`", symbol);
+            }
+
+            let m = if opts.strip {
+                let mut before_strip = Vec::new();
+                m.encode_into(&mut before_strip)?;
+
+                let (m, strip_summary) =
+                    find_malloc::strip_custom_sections(m, &opts.keep_sections)?;
+                for name in &strip_summary.removed {
+                    log::info!("stripped custom section `{}`", name);
+                }
+
+                let mut after_strip = Vec::new();
+                m.encode_into(&mut after_strip)?;
+                log::info!(
+                    "{} -> {} bytes ({} saved by stripping)",
+                    before_strip.len(),
+                    after_strip.len(),
+                    before_strip.len().saturating_sub(after_strip.len())
+                );
+
+                m
+            } else {
+                m
+            };
+
+            let mut encoded = Vec::new();
+            m.encode_into(&mut encoded)?;
+
+            if opts.verify {
+                find_malloc::verify_round_trip(&m, &encoded)?;
+            }
+
+            std::io::Write::write_all(&mut File::create(opts.output_filename)?, &encoded)?;
+        }
+        Opts::Scan(opts) => {
+            let m = decode_module(&opts.filename, opts.verbose_errors)?;
+            let report = scan(&m)?;
+
+            println!("exports ({}):", report.exports.len());
+            for name in &report.exports {
+                println!("  {}", name);
+            }
+
+            println!("imports ({}):", report.imports.len());
+            for name in &report.imports {
+                println!("  {}", name);
+            }
+
+            println!("allocator functions:");
+            for status in &report.allocators {
+                match status.func_index {
+                    Some(index) => println!(
+                        "  {} -> func #{} (exported: {})",
+                        status.symbol, index, status.already_exported
+                    ),
+                    None => println!("  {} -> not found", status.symbol),
+                }
+            }
+        }
+    }
     Ok(())
 }